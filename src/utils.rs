@@ -3,6 +3,7 @@
 use definitions::Image;
 
 use image::{
+    ColorType,
     DynamicImage,
     GenericImage,
     GrayImage,
@@ -12,30 +13,43 @@ use image::{
     Pixel,
     Primitive,
     Rgb,
-    RgbImage
+    RgbImage,
+    save_buffer
 };
 
 use quickcheck::{Arbitrary, Gen};
 use rand::Rand;
 
+use std::cmp;
+use std::env;
 use std::fmt;
 use std::path::Path;
 
-/// Implementation detail of the gray_image macros.
+use utils::view::ImgRef;
+
+pub mod generators;
+pub mod view;
+
+/// Implementation detail of the gray_image, rgb_image and rgba_image macros.
+///
+/// `$nested_array` is a rows-of-pixels-of-channels array (even for single-channel
+/// images, where each pixel is a one-element array), and `$pixel_type` is the
+/// `image` pixel wrapper (`Luma`, `Rgb`, `Rgba`, ...) to flatten it into.
 #[macro_export]
 macro_rules! image_from_nested_array {
     // This implementation is copied from the `matrix` macro
     // from https://github.com/AtheMathmo/rulinalg
-    ($nested_array:tt, $channel_type:ty) => {
+    ($nested_array:tt, $channel_type:ty, $pixel_type:ident) => {
         {
-            use image::{ImageBuffer, Luma};
+            use image::{ImageBuffer, $pixel_type};
             let rows = $nested_array.len();
             let cols = $nested_array[0].len();
             let data_as_flat_array: Vec<_> = $nested_array.into_iter()
                 .flat_map(|row| row.into_iter())
+                .flat_map(|pixel| pixel.into_iter())
                 .cloned()
                 .collect();
-            ImageBuffer::<Luma<$channel_type>, Vec<$channel_type>>::from_raw(cols as u32, rows as u32, data_as_flat_array).unwrap()
+            ImageBuffer::<$pixel_type<$channel_type>, Vec<$channel_type>>::from_raw(cols as u32, rows as u32, data_as_flat_array).unwrap()
         }
     }
 }
@@ -75,8 +89,8 @@ macro_rules! gray_image {
     };
     ($( $( $x: expr ),*);*) => {
         {
-            let data_as_nested_array = [ $( [ $($x),* ] ),* ];
-            image_from_nested_array!(data_as_nested_array, u8)
+            let data_as_nested_array = [ $( [ $( [$x] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u8, Luma)
         }
     }
 }
@@ -95,8 +109,8 @@ macro_rules! gray_image_i16 {
     };
     ($( $( $x: expr ),*);*) => {
         {
-            let data_as_nested_array = [ $( [ $($x),* ] ),* ];
-            image_from_nested_array!(data_as_nested_array, i16)
+            let data_as_nested_array = [ $( [ $( [$x] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, i16, Luma)
         }
     }
 }
@@ -115,8 +129,8 @@ macro_rules! gray_image_u16 {
     };
     ($( $( $x: expr ),*);*) => {
         {
-            let data_as_nested_array = [ $( [ $($x),* ] ),* ];
-            image_from_nested_array!(data_as_nested_array, u16)
+            let data_as_nested_array = [ $( [ $( [$x] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u16, Luma)
         }
     }
 }
@@ -135,8 +149,8 @@ macro_rules! gray_image_i32 {
     };
     ($( $( $x: expr ),*);*) => {
         {
-            let data_as_nested_array = [ $( [ $($x),* ] ),* ];
-            image_from_nested_array!(data_as_nested_array, i32)
+            let data_as_nested_array = [ $( [ $( [$x] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, i32, Luma)
         }
     }
 }
@@ -155,8 +169,111 @@ macro_rules! gray_image_u32 {
     };
     ($( $( $x: expr ),*);*) => {
         {
-            let data_as_nested_array = [ $( [ $($x),* ] ),* ];
-            image_from_nested_array!(data_as_nested_array, u32)
+            let data_as_nested_array = [ $( [ $( [$x] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u32, Luma)
+        }
+    }
+}
+
+/// Helper for defining RGB images with u8 subpixels. Columns (pixels) are
+/// separated by commas and rows by semi-colons, with each pixel written as
+/// an array of its channel values.
+///
+/// Calls `RgbImage::from_raw`.
+///
+/// # Examples
+/// ```
+/// # extern crate image;
+/// # #[macro_use]
+/// # extern crate imageproc;
+/// # fn main() {
+/// use image::RgbImage;
+///
+/// let image = rgb_image!(
+///     [1, 2, 3], [4, 5, 6];
+///     [7, 8, 9], [10, 11, 12]);
+///
+/// let equivalent = RgbImage::from_raw(2, 2, vec![
+///     1, 2, 3, 4, 5, 6,
+///     7, 8, 9, 10, 11, 12
+/// ]).unwrap();
+///
+/// assert_pixels_eq!(image, equivalent);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! rgb_image {
+    () => {
+        {
+            use image::{ImageBuffer, Rgb};
+            ImageBuffer::<Rgb<u8>, Vec<u8>>::new(0, 0)
+        }
+    };
+    ($( $( [$($x: expr),*] ),*);*) => {
+        {
+            let data_as_nested_array = [ $( [ $( [$($x),*] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u8, Rgb)
+        }
+    }
+}
+
+/// Helper for defining RGB images with u16 subpixels.
+///
+/// See the [`rgb_image`](macro.rgb_image.html) documentation for examples.
+#[macro_export]
+macro_rules! rgb_image_u16 {
+    () => {
+        {
+            use image::{ImageBuffer, Rgb};
+            ImageBuffer::<Rgb<u16>, Vec<u16>>::new(0, 0)
+        }
+    };
+    ($( $( [$($x: expr),*] ),*);*) => {
+        {
+            let data_as_nested_array = [ $( [ $( [$($x),*] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u16, Rgb)
+        }
+    }
+}
+
+/// Helper for defining RGBA images with u8 subpixels. Columns (pixels) are
+/// separated by commas and rows by semi-colons, with each pixel written as
+/// an array of its channel values.
+///
+/// Calls `RgbaImage::from_raw`.
+///
+/// See the [`rgb_image`](macro.rgb_image.html) documentation for examples.
+#[macro_export]
+macro_rules! rgba_image {
+    () => {
+        {
+            use image::{ImageBuffer, Rgba};
+            ImageBuffer::<Rgba<u8>, Vec<u8>>::new(0, 0)
+        }
+    };
+    ($( $( [$($x: expr),*] ),*);*) => {
+        {
+            let data_as_nested_array = [ $( [ $( [$($x),*] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u8, Rgba)
+        }
+    }
+}
+
+/// Helper for defining RGBA images with u16 subpixels.
+///
+/// See the [`rgb_image`](macro.rgb_image.html) documentation for examples.
+#[macro_export]
+macro_rules! rgba_image_u16 {
+    () => {
+        {
+            use image::{ImageBuffer, Rgba};
+            ImageBuffer::<Rgba<u16>, Vec<u16>>::new(0, 0)
+        }
+    };
+    ($( $( [$($x: expr),*] ),*);*) => {
+        {
+            let data_as_nested_array = [ $( [ $( [$($x),*] ),* ] ),* ];
+            image_from_nested_array!(data_as_nested_array, u16, Rgba)
         }
     }
 }
@@ -207,6 +324,29 @@ macro_rules! assert_pixels_eq {
      })
 }
 
+/// Like `assert_pixels_eq!`, but if the `IMAGEPROC_DIFF_DIR` environment
+/// variable is set and the assertion fails, also writes a visual diff image
+/// (see [`diff_image`](fn.diff_image.html)) to that directory before
+/// panicking, named after the calling test function.
+///
+/// Useful when a failure is spread across a whole region rather than a
+/// handful of pixels, where `describe_pixel_diffs`'s first-five-mismatches
+/// text summary isn't enough to see what went wrong.
+#[macro_export]
+macro_rules! assert_pixels_eq_diff {
+    ($actual:expr, $expected:expr) => ({
+        assert_dimensions_match!($actual, $expected);
+        match $crate::utils::pixel_diff_summary(&$actual, &$expected) {
+            None => {},
+            Some(err) => {
+                $crate::utils::maybe_save_diff_image(
+                    &$actual, &$expected, &$crate::utils::current_test_name());
+                panic!(err)
+            }
+        };
+     })
+}
+
 /// Panics if any pixels differ between the two images by more than the
 /// given tolerance in a single channel.
 #[macro_export]
@@ -244,6 +384,41 @@ macro_rules! assert_pixels_eq_within {
     })
 }
 
+/// Panics if the peak signal-to-noise ratio between the two images, in
+/// decibels, is below the given threshold. Images are assumed to have
+/// `u8` subpixels, so the dynamic range used is 255.
+///
+/// PSNR is far more forgiving of small, spread-out pixel differences than
+/// [`assert_pixels_eq_within`](macro.assert_pixels_eq_within.html), which
+/// makes it a better fit for asserting on the output of filters where the
+/// overall structure matters more than individual pixels.
+#[macro_export]
+macro_rules! assert_image_psnr {
+    ($actual:expr, $expected:expr, $threshold:expr) => ({
+        assert_dimensions_match!($actual, $expected);
+        let achieved = $crate::utils::psnr(&$actual, &$expected, 255.0);
+        if achieved < $threshold {
+            panic!("PSNR too low. achieved: {}dB, required: {}dB", achieved, $threshold)
+        }
+    })
+}
+
+/// Panics if the mean structural similarity (SSIM) between the two images
+/// is below the given threshold. Images are assumed to have `u8` subpixels,
+/// so the dynamic range used is 255.
+///
+/// See [`ssim`](fn.ssim.html) for details of how the metric is computed.
+#[macro_export]
+macro_rules! assert_image_ssim {
+    ($actual:expr, $expected:expr, $threshold:expr) => ({
+        assert_dimensions_match!($actual, $expected);
+        let achieved = $crate::utils::ssim(&$actual, &$expected, 255.0);
+        if achieved < $threshold {
+            panic!("SSIM too low. achieved: {}, required: {}", achieved, $threshold)
+        }
+    })
+}
+
 /// Panics if image dimensions do not match.
 #[macro_export]
 macro_rules! assert_dimensions_match {
@@ -298,6 +473,229 @@ pub fn describe_pixel_diffs<I, P>(diffs: I) -> String
     err
 }
 
+/// Builds an `RgbImage` visualising the differences between `actual` and
+/// `expected`: a dimmed copy of `expected`, with mismatched pixels
+/// overlaid as a heatmap (blue for a small difference, red for a large one)
+/// of their maximum absolute per-channel difference. Panics if the image
+/// dimensions don't match.
+pub fn diff_image<I, J, P>(actual: &I, expected: &J) -> RgbImage
+    where P: Pixel,
+          P::Subpixel: Primitive,
+          I: GenericImage<Pixel=P>,
+          J: GenericImage<Pixel=P>
+{
+    assert_dimensions_match!(actual, expected);
+
+    let (width, height) = expected.dimensions();
+    let mut diff = RgbImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let a = actual.get_pixel(x, y);
+            let e = expected.get_pixel(x, y);
+            let max_diff = max_channel_diff(&a, &e);
+
+            let pixel = if max_diff == 0.0 {
+                dimmed_gray(&e)
+            } else {
+                heat_color(max_diff)
+            };
+            diff.put_pixel(x, y, pixel);
+        }
+    }
+
+    diff
+}
+
+/// The name of the current test, so that each failing `assert_pixels_eq_diff!`
+/// call gets its own diff image instead of every failure in a module
+/// clobbering the same file. Under the default test harness, tests run on
+/// their own thread named after their fully-qualified path; outside of that
+/// harness there's no such name, so this falls back to `"unknown"`.
+pub fn current_test_name() -> String {
+    ::std::thread::current().name().unwrap_or("unknown").to_string()
+}
+
+/// Writes a [`diff_image`](fn.diff_image.html) of `actual` and `expected` to
+/// the directory named by the `IMAGEPROC_DIFF_DIR` environment variable,
+/// using `name` (with `::` replaced by `_`) as the file name. Does nothing
+/// if that variable isn't set, or if writing the image fails. Called by
+/// [`assert_pixels_eq_diff`](macro.assert_pixels_eq_diff.html).
+pub fn maybe_save_diff_image<I, J, P>(actual: &I, expected: &J, name: &str)
+    where P: Pixel,
+          P::Subpixel: Primitive,
+          I: GenericImage<Pixel=P>,
+          J: GenericImage<Pixel=P>
+{
+    let dir = match env::var("IMAGEPROC_DIFF_DIR") {
+        Ok(dir) => dir,
+        Err(_) => return
+    };
+    let diff = diff_image(actual, expected);
+    let path = Path::new(&dir).join(format!("{}.png", name.replace("::", "_")));
+    let (width, height) = diff.dimensions();
+    let _ = save_buffer(&path, &diff, width, height, ColorType::RGB(8));
+}
+
+fn max_channel_diff<P>(actual: &P, expected: &P) -> f64
+    where P: Pixel,
+          P::Subpixel: Primitive
+{
+    actual.channels().iter().zip(expected.channels().iter())
+        .map(|(a, e)| (a.to_f64().unwrap() - e.to_f64().unwrap()).abs())
+        .fold(0.0, f64::max)
+}
+
+fn dimmed_gray<P>(pixel: &P) -> Rgb<u8>
+    where P: Pixel,
+          P::Subpixel: Primitive
+{
+    let channels = pixel.channels();
+    let mean = channels.iter().map(|c| c.to_f64().unwrap()).sum::<f64>() / channels.len() as f64;
+    let dimmed = (mean * 0.4).round() as u8;
+    Rgb([dimmed, dimmed, dimmed])
+}
+
+/// Maps a maximum per-channel difference in `[0, 255]` to a blue (small
+/// difference) - red (large difference) heat color.
+fn heat_color(max_diff: f64) -> Rgb<u8> {
+    let t = (max_diff / 255.0).min(1.0);
+    Rgb([(t * 255.0).round() as u8, 0, ((1.0 - t) * 255.0).round() as u8])
+}
+
+/// Computes the peak signal-to-noise ratio between two images, in decibels.
+/// Higher values indicate more similar images; identical images have an
+/// infinite PSNR. `max` is the maximum possible subpixel value, e.g. 255.0
+/// for images with `u8` subpixels. Panics if the image dimensions don't match.
+pub fn psnr<I, J, P>(actual: &I, expected: &J, max: f64) -> f64
+    where P: Pixel,
+          P::Subpixel: Primitive,
+          I: GenericImage<Pixel=P>,
+          J: GenericImage<Pixel=P>
+{
+    assert_dimensions_match!(actual, expected);
+
+    let mse = mean_squared_error(actual, expected);
+    if mse == 0.0 {
+        return ::std::f64::INFINITY;
+    }
+    10.0 * (max * max / mse).log10()
+}
+
+fn mean_squared_error<I, J, P>(actual: &I, expected: &J) -> f64
+    where P: Pixel,
+          P::Subpixel: Primitive,
+          I: GenericImage<Pixel=P>,
+          J: GenericImage<Pixel=P>
+{
+    let mut sum = 0.0f64;
+    let mut count = 0u64;
+    for ((_, _, p), (_, _, q)) in GenericImage::pixels(actual).zip(GenericImage::pixels(expected)) {
+        for (sp, sq) in p.channels().iter().zip(q.channels().iter()) {
+            let diff = sp.to_f64().unwrap() - sq.to_f64().unwrap();
+            sum += diff * diff;
+            count += 1;
+        }
+    }
+    sum / count as f64
+}
+
+/// Computes the mean structural similarity (SSIM) between two images, sliding
+/// a `WINDOW_SIZE x WINDOW_SIZE` box window over both. Returns a value in
+/// `[-1, 1]`, with `1` meaning the images are identical. `max` is the maximum
+/// possible subpixel value, e.g. 255.0 for images with `u8` subpixels.
+/// Panics if the image dimensions don't match.
+///
+/// See Wang et al., "Image Quality Assessment: From Error Visibility to
+/// Structural Similarity", IEEE Transactions on Image Processing, 2004.
+pub fn ssim<I, J, P>(actual: &I, expected: &J, max: f64) -> f64
+    where P: Pixel,
+          P::Subpixel: Primitive,
+          I: GenericImage<Pixel=P>,
+          J: GenericImage<Pixel=P>
+{
+    assert_dimensions_match!(actual, expected);
+
+    const WINDOW_SIZE: u32 = 8;
+    let c1 = (0.01 * max).powi(2);
+    let c2 = (0.03 * max).powi(2);
+
+    let width = actual.width();
+    let height = actual.height();
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+
+    let channel_count = P::channel_count() as usize;
+    let mut sum = 0.0f64;
+    let mut windows = 0u64;
+
+    let mut y = 0;
+    while y < height {
+        let window_height = cmp::min(WINDOW_SIZE, height - y);
+        let mut x = 0;
+        while x < width {
+            let window_width = cmp::min(WINDOW_SIZE, width - x);
+            for channel in 0..channel_count {
+                sum += window_ssim(
+                    actual, expected, x, y, window_width, window_height, channel, c1, c2);
+                windows += 1;
+            }
+            x += WINDOW_SIZE;
+        }
+        y += WINDOW_SIZE;
+    }
+
+    sum / windows as f64
+}
+
+/// SSIM of a single channel over a single `width x height` window starting at `(x, y)`.
+fn window_ssim<I, J, P>(
+    actual: &I,
+    expected: &J,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    channel: usize,
+    c1: f64,
+    c2: f64
+) -> f64
+    where P: Pixel,
+          P::Subpixel: Primitive,
+          I: GenericImage<Pixel=P>,
+          J: GenericImage<Pixel=P>
+{
+    let n = (width * height) as f64;
+
+    let mut xs = Vec::with_capacity(n as usize);
+    let mut ys = Vec::with_capacity(n as usize);
+    for dy in 0..height {
+        for dx in 0..width {
+            xs.push(actual.get_pixel(x + dx, y + dy).channels()[channel].to_f64().unwrap());
+            ys.push(expected.get_pixel(x + dx, y + dy).channels()[channel].to_f64().unwrap());
+        }
+    }
+
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+    let mut covar_xy = 0.0;
+    for i in 0..xs.len() {
+        var_x += (xs[i] - mean_x).powi(2);
+        var_y += (ys[i] - mean_y).powi(2);
+        covar_xy += (xs[i] - mean_x) * (ys[i] - mean_y);
+    }
+    var_x /= n;
+    var_y /= n;
+    covar_xy /= n;
+
+    ((2.0 * mean_x * mean_y + c1) * (2.0 * covar_xy + c2))
+        / ((mean_x.powi(2) + mean_y.powi(2) + c1) * (var_x + var_y + c2))
+}
+
 /// Loads image at given path, panicking on failure.
 pub fn load_image_or_panic<P: AsRef<Path> + fmt::Debug>(path: P) -> DynamicImage {
      open(path.as_ref()).expect(&format!("Could not load image at {:?}", path.as_ref()))
@@ -369,9 +767,8 @@ pub trait ArbitraryPixel {
     fn arbitrary<G: Gen>(g: &mut G) -> Self;
 }
 
-fn shrink<I>(image: &I) -> Box<Iterator<Item=Image<I::Pixel>>>
-    where I: GenericImage,
-          I::Pixel: 'static
+fn shrink<P>(image: &Image<P>) -> Box<Iterator<Item=Image<P>>>
+    where P: Pixel + 'static
 {
     let mut subs = vec![];
 
@@ -394,17 +791,16 @@ fn shrink<I>(image: &I) -> Box<Iterator<Item=Image<I::Pixel>>>
     Box::new(subs.into_iter())
 }
 
-fn copy_sub<I>(image: &I, x: u32, y: u32, width: u32, height: u32) -> Image<I::Pixel>
-    where I: GenericImage,
-          I::Pixel: 'static
+/// Copies a `width x height` rectangle starting at `(x, y)` out of `image`.
+/// Only the destination buffer is allocated; reading the source rectangle
+/// goes through a zero-copy [`ImgRef`](view/struct.ImgRef.html) view.
+fn copy_sub<P>(image: &Image<P>, x: u32, y: u32, width: u32, height: u32) -> Image<P>
+    where P: Pixel + 'static
 {
+    let view = ImgRef::of(image).sub_view(x, y, width, height);
     let mut out = ImageBuffer::new(width, height);
-    for dy in 0..height {
-        let oy = y + dy;
-        for dx in 0..width {
-            let ox = x + dx;
-            out.put_pixel(dx, dy, image.get_pixel(ox, oy));
-        }
+    for (dx, dy, pixel) in view.pixels() {
+        out.put_pixel(dx, dy, pixel);
     }
     out
 }
@@ -439,7 +835,7 @@ impl<T: Rand + Send + Primitive> ArbitraryPixel for Luma<T> {
 
 #[cfg(test)]
 mod test {
-    use image::{GrayImage, ImageBuffer, Luma};
+    use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage, Rgba};
 
     #[test]
     fn test_gray_image_empty() {
@@ -531,6 +927,71 @@ mod test {
         assert_pixels_eq!(image, expected);
     }
 
+    #[test]
+    fn test_rgb_image_empty() {
+        let image = rgb_image!();
+        assert_eq!(image.dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn test_rgb_image_single_element() {
+        let image = rgb_image!([1, 2, 3]);
+        let expected = RgbImage::from_raw(1, 1, vec![1, 2, 3]).unwrap();
+        assert_pixels_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_rgb_image_multiple_rows_and_columns() {
+        let image = rgb_image!(
+            [1, 2, 3], [4, 5, 6];
+            [7, 8, 9], [10, 11, 12]);
+
+        let expected = RgbImage::from_raw(2, 2, vec![
+            1, 2, 3, 4, 5, 6,
+            7, 8, 9, 10, 11, 12
+        ]).unwrap();
+
+        assert_pixels_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_rgb_image_u16() {
+        let image = rgb_image_u16!(
+            [1, 2, 3], [4, 5, 6]);
+
+        let expected = ImageBuffer::<Rgb<u16>, Vec<u16>>::from_raw(2, 1, vec![
+            1u16, 2, 3, 4, 5, 6
+        ]).unwrap();
+
+        assert_pixels_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_rgba_image_multiple_rows_and_columns() {
+        let image = rgba_image!(
+            [1, 2, 3, 4], [5, 6, 7, 8];
+            [9, 10, 11, 12], [13, 14, 15, 16]);
+
+        let expected = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(2, 2, vec![
+            1, 2, 3, 4, 5, 6, 7, 8,
+            9, 10, 11, 12, 13, 14, 15, 16
+        ]).unwrap();
+
+        assert_pixels_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_rgba_image_u16() {
+        let image = rgba_image_u16!(
+            [1, 2, 3, 4], [5, 6, 7, 8]);
+
+        let expected = ImageBuffer::<Rgba<u16>, Vec<u16>>::from_raw(2, 1, vec![
+            1u16, 2, 3, 4, 5, 6, 7, 8
+        ]).unwrap();
+
+        assert_pixels_eq!(image, expected);
+    }
+
     #[test]
     fn test_assert_pixels_eq_passes() {
         let image = gray_image!(
@@ -580,4 +1041,181 @@ mod test {
 
         assert_pixels_eq_within!(diff, image, 1);
     }
+
+    #[test]
+    fn test_psnr_identical_images_is_infinite() {
+        let image = gray_image!(
+            00, 01, 02;
+            10, 11, 12);
+
+        assert_eq!(super::psnr(&image, &image, 255.0), ::std::f64::INFINITY);
+    }
+
+    #[test]
+    fn test_assert_image_psnr_passes() {
+        let image = gray_image!(
+            00, 01, 02;
+            10, 11, 12);
+
+        let diff = gray_image!(
+            00, 02, 02;
+            10, 11, 12);
+
+        assert_image_psnr!(diff, image, 30.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_assert_image_psnr_fails() {
+        let image = gray_image!(
+            000, 001, 002;
+            010, 011, 012);
+
+        let diff = gray_image!(
+            000, 200, 002;
+            010, 011, 012);
+
+        assert_image_psnr!(diff, image, 30.0);
+    }
+
+    #[test]
+    fn test_ssim_identical_images_is_one() {
+        let image = gray_image!(
+            00, 01, 02, 03;
+            10, 11, 12, 13);
+
+        assert_eq!(super::ssim(&image, &image, 255.0), 1.0);
+    }
+
+    #[test]
+    fn test_assert_image_ssim_passes() {
+        let image = gray_image!(
+            00, 01, 02, 03;
+            10, 11, 12, 13);
+
+        let diff = gray_image!(
+            00, 02, 02, 03;
+            10, 11, 12, 13);
+
+        assert_image_ssim!(diff, image, 0.9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_assert_image_ssim_fails() {
+        let image = gray_image!(
+            000, 001, 002, 003;
+            010, 011, 012, 013);
+
+        let diff = gray_image!(
+            000, 200, 002, 003;
+            010, 011, 012, 013);
+
+        assert_image_ssim!(diff, image, 0.9);
+    }
+
+    #[test]
+    fn test_diff_image_dims_match_input() {
+        let image = gray_image!(
+            00, 01, 02;
+            10, 11, 12);
+
+        let diff = super::diff_image(&image, &image);
+        assert_eq!(diff.dimensions(), image.dimensions());
+    }
+
+    #[test]
+    fn test_diff_image_highlights_mismatch() {
+        let image = gray_image!(
+            05, 05;
+            05, 05);
+
+        let actual = gray_image!(
+            05, 05;
+            05, 99);
+
+        let diff = super::diff_image(&actual, &image);
+
+        // Unchanged pixels are rendered as a dimmed copy of `image`, so they
+        // should all share the same color.
+        assert_eq!(diff.get_pixel(0, 0), diff.get_pixel(1, 0));
+        assert_eq!(diff.get_pixel(0, 0), diff.get_pixel(0, 1));
+        // The mismatched pixel should stand out from the dimmed background.
+        assert!(diff.get_pixel(1, 1) != diff.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_maybe_save_diff_image_writes_file_when_env_var_set() {
+        use std::env;
+        use std::fs;
+
+        let image = gray_image!(00, 01; 02, 03);
+        let actual = gray_image!(00, 99; 02, 03);
+
+        let dir = env::temp_dir().join("imageproc_test_maybe_save_diff_image");
+        fs::create_dir_all(&dir).unwrap();
+        env::set_var("IMAGEPROC_DIFF_DIR", &dir);
+
+        super::maybe_save_diff_image(&actual, &image, "utils::test::a_test_name");
+
+        let path = dir.join("utils_test_a_test_name.png");
+        assert!(path.exists());
+
+        env::remove_var("IMAGEPROC_DIFF_DIR");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_assert_pixels_eq_diff_names_image_after_each_failing_test() {
+        use std::env;
+        use std::fs;
+        use std::panic;
+        use std::sync::Arc;
+        use std::thread;
+
+        const FAKE_TEST_NAMES: [&str; 2] =
+            ["utils::test::fake_test_a", "utils::test::fake_test_b"];
+
+        // Simulates two different failing tests (each test function runs on
+        // its own named thread under the default harness) to check that
+        // `assert_pixels_eq_diff!` gives each one its own diff image instead
+        // of both clobbering a single `<module>.png`.
+        fn fail_with_diff(name: &str) {
+            thread::Builder::new().name(name.to_string()).spawn(|| {
+                let image = gray_image!(00, 01; 02, 03);
+                let actual = gray_image!(00, 99; 02, 03);
+                let result = panic::catch_unwind(|| {
+                    assert_pixels_eq_diff!(actual, image);
+                });
+                assert!(result.is_err());
+            }).unwrap().join().unwrap();
+        }
+
+        let dir = env::temp_dir().join("imageproc_test_assert_pixels_eq_diff_no_clobber");
+        fs::create_dir_all(&dir).unwrap();
+        env::set_var("IMAGEPROC_DIFF_DIR", &dir);
+
+        // The panic hook is process-global, so only silence it for the two
+        // threads this test deliberately panics on; any other test panicking
+        // concurrently on its own thread still gets its message printed.
+        let default_hook = Arc::new(panic::take_hook());
+        let hook_for_wrapper = Arc::clone(&default_hook);
+        panic::set_hook(Box::new(move |info| {
+            let is_simulated = thread::current().name()
+                .map_or(false, |name| FAKE_TEST_NAMES.contains(&name));
+            if !is_simulated {
+                hook_for_wrapper(info);
+            }
+        }));
+        fail_with_diff(FAKE_TEST_NAMES[0]);
+        fail_with_diff(FAKE_TEST_NAMES[1]);
+        drop(panic::take_hook());
+        panic::set_hook(Arc::try_unwrap(default_hook).unwrap_or_else(|_| Box::new(|_| {})));
+
+        assert!(dir.join("utils_test_fake_test_a.png").exists());
+        assert!(dir.join("utils_test_fake_test_b.png").exists());
+
+        env::remove_var("IMAGEPROC_DIFF_DIR");
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
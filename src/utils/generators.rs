@@ -0,0 +1,360 @@
+//! Structured test-image generators for property testing.
+//!
+//! `TestBuffer`'s `Arbitrary` instance produces uniform noise, which rarely
+//! triggers the structural bugs (region boundaries, gradients, flat areas)
+//! that image algorithms actually have. The generators here build images with
+//! that kind of structure instead, and [`StructuredTestImage`] wires a subset
+//! of them into an `Arbitrary` instance that randomly picks a generator and
+//! its parameters.
+
+use image::{ImageBuffer, Pixel, Primitive};
+use quickcheck::{Arbitrary, Gen};
+
+use std::cmp;
+
+use definitions::Image;
+use utils::{shrink, small_image_dimensions, ArbitraryPixel};
+
+/// An image filled entirely with a single color.
+pub fn solid<P: Pixel + 'static>(width: u32, height: u32, color: P) -> Image<P> {
+    ImageBuffer::from_fn(width, height, |_, _| color)
+}
+
+/// An image whose pixels vary linearly between `low` (at the top-left corner)
+/// and `high` (at the bottom-right corner).
+pub fn gradient<P>(width: u32, height: u32, low: P, high: P) -> Image<P>
+    where P: Pixel + 'static,
+          P::Subpixel: Primitive + FromF64
+{
+    let denom = cmp::max(width, 1) as f64 - 1.0 + cmp::max(height, 1) as f64 - 1.0;
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let t = if denom <= 0.0 { 0.0 } else { (x + y) as f64 / denom };
+        lerp_pixel(&low, &high, t)
+    })
+}
+
+/// An image of alternating `cell_size x cell_size` blocks of `a` and `b`.
+pub fn checkerboard<P: Pixel + 'static>(width: u32, height: u32, cell_size: u32, a: P, b: P) -> Image<P> {
+    let cell_size = cmp::max(cell_size, 1);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        if (x / cell_size + y / cell_size) % 2 == 0 { a } else { b }
+    })
+}
+
+/// A single shape painted over a background by `random_rects`/
+/// `random_disks`, kept around (in [`StructuredTestImage`]) so `shrink` can
+/// drop shapes one at a time instead of only shrinking the canvas.
+#[derive(Clone)]
+enum Shape<P> {
+    Rect { x: u32, y: u32, width: u32, height: u32, color: P },
+    Disk { cx: i64, cy: i64, radius: i64, color: P },
+}
+
+fn generate_rects<P, G>(width: u32, height: u32, count: u32, g: &mut G) -> Vec<Shape<P>>
+    where P: ArbitraryPixel,
+          G: Gen
+{
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    (0..count).map(|_| {
+        let x = g.gen_range(0, width);
+        let y = g.gen_range(0, height);
+        let width = g.gen_range(1, width - x + 1);
+        let height = g.gen_range(1, height - y + 1);
+        let color = ArbitraryPixel::arbitrary(g);
+        Shape::Rect { x: x, y: y, width: width, height: height, color: color }
+    }).collect()
+}
+
+fn generate_disks<P, G>(width: u32, height: u32, count: u32, g: &mut G) -> Vec<Shape<P>>
+    where P: ArbitraryPixel,
+          G: Gen
+{
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let max_radius = cmp::max(cmp::min(width, height) / 2, 1) as i64;
+    (0..count).map(|_| {
+        let cx = g.gen_range(0, width) as i64;
+        let cy = g.gen_range(0, height) as i64;
+        let radius = g.gen_range(1, max_radius + 1);
+        let color = ArbitraryPixel::arbitrary(g);
+        Shape::Disk { cx: cx, cy: cy, radius: radius, color: color }
+    }).collect()
+}
+
+fn paint_shape<P: Pixel>(image: &mut Image<P>, width: u32, height: u32, shape: &Shape<P>) {
+    match *shape {
+        Shape::Rect { x, y, width: w, height: h, color } => {
+            for py in y..y + h {
+                for px in x..x + w {
+                    image.put_pixel(px, py, color);
+                }
+            }
+        }
+        Shape::Disk { cx, cy, radius, color } => {
+            for y in 0..height as i64 {
+                for x in 0..width as i64 {
+                    let (dx, dy) = (x - cx, y - cy);
+                    if dx * dx + dy * dy <= radius * radius {
+                        image.put_pixel(x as u32, y as u32, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn paint_shapes<P: Pixel + 'static>(width: u32, height: u32, background: P, shapes: &[Shape<P>]) -> Image<P> {
+    let mut image = solid(width, height, background);
+    for shape in shapes {
+        paint_shape(&mut image, width, height, shape);
+    }
+    image
+}
+
+/// A `background`-colored image with `count` solid, randomly-placed,
+/// randomly-sized, randomly-colored axis-aligned rectangles painted over it.
+pub fn random_rects<P, G>(width: u32, height: u32, count: u32, background: P, g: &mut G) -> Image<P>
+    where P: Pixel + ArbitraryPixel + 'static,
+          P::Subpixel: Send,
+          G: Gen
+{
+    let shapes = generate_rects(width, height, count, g);
+    paint_shapes(width, height, background, &shapes)
+}
+
+/// A `background`-colored image with `count` solid, randomly-placed,
+/// randomly-sized, randomly-colored filled disks painted over it.
+pub fn random_disks<P, G>(width: u32, height: u32, count: u32, background: P, g: &mut G) -> Image<P>
+    where P: Pixel + ArbitraryPixel + 'static,
+          P::Subpixel: Send,
+          G: Gen
+{
+    let shapes = generate_disks(width, height, count, g);
+    paint_shapes(width, height, background, &shapes)
+}
+
+fn lerp_pixel<P>(low: &P, high: &P, t: f64) -> P
+    where P: Pixel,
+          P::Subpixel: Primitive + FromF64
+{
+    let mut out = *low;
+    let lc = low.channels();
+    let hc = high.channels();
+    for (o, (l, h)) in out.channels_mut().iter_mut().zip(lc.iter().zip(hc.iter())) {
+        let v = l.to_f64().unwrap() + t * (h.to_f64().unwrap() - l.to_f64().unwrap());
+        *o = FromF64::from_f64(v);
+    }
+    out
+}
+
+/// Workaround for not being able to define a generic `f64 -> Subpixel`
+/// conversion for pixel types defined in other modules.
+pub(crate) trait FromF64 {
+    /// Converts `v`, clamping it to this type's representable range.
+    fn from_f64(v: f64) -> Self;
+}
+
+impl FromF64 for u8 {
+    fn from_f64(v: f64) -> Self { v.max(0.0).min(u8::max_value() as f64).round() as u8 }
+}
+impl FromF64 for u16 {
+    fn from_f64(v: f64) -> Self { v.max(0.0).min(u16::max_value() as f64).round() as u16 }
+}
+impl FromF64 for u32 {
+    fn from_f64(v: f64) -> Self { v.max(0.0).min(u32::max_value() as f64).round() as u32 }
+}
+impl FromF64 for i16 {
+    fn from_f64(v: f64) -> Self {
+        v.max(i16::min_value() as f64).min(i16::max_value() as f64).round() as i16
+    }
+}
+impl FromF64 for i32 {
+    fn from_f64(v: f64) -> Self {
+        v.max(i32::min_value() as f64).min(i32::max_value() as f64).round() as i32
+    }
+}
+
+/// The shapes (if any) painted over the background to produce a
+/// [`StructuredTestImage`], kept around so its `shrink` can drop a shape
+/// instead of only shrinking the canvas.
+#[derive(Clone)]
+enum ShapeLayer<P> {
+    /// Not generated by painting shapes over a background (a gradient,
+    /// checkerboard, or solid image); only the canvas itself can shrink.
+    None,
+    /// Generated by painting `shapes` over `background`. Dimensions aren't
+    /// stored here; they're read back from the image itself when needed.
+    Shapes { background: P, shapes: Vec<Shape<P>> },
+}
+
+/// Wrapper for image buffers like `TestBuffer`, but whose `Arbitrary`
+/// instance draws from the structured generators in this module
+/// (gradients, checkerboards, solids, random shapes) rather than pure noise.
+/// Shrinks toward both smaller dimensions and, for shape-based images,
+/// fewer shapes.
+#[derive(Clone)]
+pub struct StructuredTestImage<P: Pixel>(pub Image<P>, ShapeLayer<P>);
+
+impl<P: Pixel + ArbitraryPixel + Send + 'static> Arbitrary for StructuredTestImage<P>
+    where P::Subpixel: Send + Primitive + FromF64
+{
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let (width, height) = small_image_dimensions(g);
+        let background: P = ArbitraryPixel::arbitrary(g);
+        let foreground: P = ArbitraryPixel::arbitrary(g);
+        let shape_count = g.gen_range(0, 4);
+
+        match g.gen_range(0, 5) {
+            0 => StructuredTestImage(gradient(width, height, background, foreground), ShapeLayer::None),
+            1 => StructuredTestImage(
+                checkerboard(width, height, 1 + g.gen_range(0, 4), background, foreground),
+                ShapeLayer::None),
+            2 => StructuredTestImage(solid(width, height, background), ShapeLayer::None),
+            3 => {
+                let shapes = generate_rects(width, height, shape_count, g);
+                let image = paint_shapes(width, height, background, &shapes);
+                StructuredTestImage(image, ShapeLayer::Shapes { background: background, shapes: shapes })
+            }
+            _ => {
+                let shapes = generate_disks(width, height, shape_count, g);
+                let image = paint_shapes(width, height, background, &shapes);
+                StructuredTestImage(image, ShapeLayer::Shapes { background: background, shapes: shapes })
+            }
+        }
+    }
+
+    fn shrink(&self) -> Box<Iterator<Item=StructuredTestImage<P>>> {
+        let mut variants: Vec<_> = shrink(&self.0)
+            .map(|image| StructuredTestImage(image, ShapeLayer::None))
+            .collect();
+
+        if let ShapeLayer::Shapes { background, ref shapes } = self.1 {
+            let (width, height) = (self.0.width(), self.0.height());
+            for i in 0..shapes.len() {
+                let mut fewer = shapes.clone();
+                fewer.remove(i);
+                let image = paint_shapes(width, height, background, &fewer);
+                variants.push(StructuredTestImage(
+                    image, ShapeLayer::Shapes { background: background, shapes: fewer }));
+            }
+        }
+
+        Box::new(variants.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{checkerboard, gradient, paint_shapes, random_disks, random_rects, solid, Shape,
+                ShapeLayer, StructuredTestImage};
+    use image::{GenericImage, Luma};
+    use quickcheck::{Arbitrary, StdGen};
+
+    #[test]
+    fn test_solid_fills_every_pixel() {
+        let image = solid(3, 2, Luma([7u8]));
+        for (_, _, pixel) in GenericImage::pixels(&image) {
+            assert_eq!(pixel, Luma([7u8]));
+        }
+    }
+
+    #[test]
+    fn test_gradient_interpolates_between_endpoints() {
+        let image = gradient(3, 1, Luma([0u8]), Luma([255u8]));
+        assert_eq!(image.get_pixel(0, 0), &Luma([0u8]));
+        assert_eq!(image.get_pixel(2, 0), &Luma([255u8]));
+    }
+
+    #[test]
+    fn test_checkerboard_alternates_per_cell() {
+        let image = checkerboard(4, 1, 1, Luma([0u8]), Luma([255u8]));
+        assert_eq!(image.get_pixel(0, 0), &Luma([0u8]));
+        assert_eq!(image.get_pixel(1, 0), &Luma([255u8]));
+        assert_eq!(image.get_pixel(2, 0), &Luma([0u8]));
+        assert_eq!(image.get_pixel(3, 0), &Luma([255u8]));
+    }
+
+    #[test]
+    fn test_random_rects_background_shows_through_when_count_zero() {
+        let mut g = StdGen::new(rand::thread_rng(), 10);
+        let image = random_rects(5, 5, 0, Luma([7u8]), &mut g);
+        for (_, _, pixel) in GenericImage::pixels(&image) {
+            assert_eq!(pixel, Luma([7u8]));
+        }
+    }
+
+    #[test]
+    fn test_random_rects_paints_over_background_when_count_positive() {
+        let mut g = StdGen::new(rand::thread_rng(), 10);
+        let image = random_rects(20, 20, 8, Luma([7u8]), &mut g);
+        assert!(GenericImage::pixels(&image).any(|(_, _, pixel)| pixel != Luma([7u8])));
+    }
+
+    #[test]
+    fn test_random_disks_background_shows_through_when_count_zero() {
+        let mut g = StdGen::new(rand::thread_rng(), 10);
+        let image = random_disks(5, 5, 0, Luma([7u8]), &mut g);
+        for (_, _, pixel) in GenericImage::pixels(&image) {
+            assert_eq!(pixel, Luma([7u8]));
+        }
+    }
+
+    #[test]
+    fn test_random_disks_paints_over_background_when_count_positive() {
+        let mut g = StdGen::new(rand::thread_rng(), 10);
+        let image = random_disks(20, 20, 8, Luma([7u8]), &mut g);
+        assert!(GenericImage::pixels(&image).any(|(_, _, pixel)| pixel != Luma([7u8])));
+    }
+
+    #[test]
+    fn test_structured_test_image_arbitrary_does_not_panic() {
+        for size in &[1, 5, 20, 100] {
+            let mut g = StdGen::new(rand::thread_rng(), *size);
+            for _ in 0..20 {
+                let _: StructuredTestImage<Luma<u8>> = Arbitrary::arbitrary(&mut g);
+            }
+        }
+    }
+
+    #[test]
+    fn test_structured_test_image_shrink_yields_no_larger_images() {
+        let mut g = StdGen::new(rand::thread_rng(), 10);
+        for _ in 0..20 {
+            let image: StructuredTestImage<Luma<u8>> = Arbitrary::arbitrary(&mut g);
+            let (width, height) = (image.0.width(), image.0.height());
+            for shrunk in image.shrink() {
+                assert!(shrunk.0.width() <= width);
+                assert!(shrunk.0.height() <= height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_structured_test_image_shrink_yields_fewer_shapes() {
+        let background = Luma([0u8]);
+        let shapes = vec![
+            Shape::Rect { x: 0, y: 0, width: 2, height: 2, color: Luma([7u8]) },
+            Shape::Rect { x: 3, y: 0, width: 2, height: 2, color: Luma([9u8]) },
+        ];
+        let image = paint_shapes(5, 5, background, &shapes);
+        let structured = StructuredTestImage(image, ShapeLayer::Shapes {
+            background: background, shapes: shapes,
+        });
+
+        // Same-size shrink variants should be the "drop one shape" variants,
+        // not just dimension-shrinks (which would all be smaller).
+        let same_size_variants: Vec<_> = structured.shrink()
+            .filter(|s| s.0.width() == 5 && s.0.height() == 5)
+            .collect();
+        assert_eq!(same_size_variants.len(), 2);
+
+        let painted_count = |image: &_| GenericImage::pixels(image).filter(|&(_, _, p)| p != background).count();
+        let original_painted = painted_count(&structured.0);
+        for variant in &same_size_variants {
+            assert!(painted_count(&variant.0) < original_painted);
+        }
+    }
+}
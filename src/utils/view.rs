@@ -0,0 +1,316 @@
+//! Borrowed, stride-based views into image buffers.
+//!
+//! `copy_sub` and algorithms built on top of it allocate a fresh buffer for
+//! every sub-region they touch, which forces anything that wants to operate
+//! on a window of a larger image (connected components, convolutions, ...)
+//! to copy first. `ImgRef`/`ImgRefMut` let such code borrow a rectangular
+//! region directly out of an existing buffer instead: `sub_view` just
+//! re-slices the underlying data, keeping the parent's row stride, without
+//! touching a single pixel.
+
+use image::Pixel;
+
+use std::cmp;
+
+use definitions::Image;
+
+/// Panics unless a `width x height` rectangle starting at `(x, y)` fits
+/// within a `parent_width x parent_height` view, using overflow-checked
+/// arithmetic so a rectangle that wraps around doesn't slip past the check.
+fn assert_in_bounds(x: u32, y: u32, width: u32, height: u32, parent_width: u32, parent_height: u32) {
+    let in_bounds = x.checked_add(width).map_or(false, |x1| x1 <= parent_width)
+        && y.checked_add(height).map_or(false, |y1| y1 <= parent_height);
+    assert!(in_bounds,
+        "sub-view rectangle ({}, {}, {}, {}) extends beyond view dimensions ({}, {})",
+        x, y, width, height, parent_width, parent_height);
+}
+
+/// A borrowed view over a `width x height` rectangle of subpixel data with a
+/// given row stride, the way `ImageBuffer` would see it if some other image's
+/// data were `&[P::Subpixel]`.
+pub struct ImgRef<'a, P>
+    where P: Pixel + 'a,
+          P::Subpixel: 'a
+{
+    data: &'a [P::Subpixel],
+    width: u32,
+    height: u32,
+    row_stride: usize
+}
+
+/// The mutable counterpart of [`ImgRef`](struct.ImgRef.html).
+pub struct ImgRefMut<'a, P>
+    where P: Pixel + 'a,
+          P::Subpixel: 'a
+{
+    data: &'a mut [P::Subpixel],
+    width: u32,
+    height: u32,
+    row_stride: usize
+}
+
+impl<'a, P> ImgRef<'a, P>
+    where P: Pixel + 'a,
+          P::Subpixel: 'a
+{
+    /// A view over the whole of `image`, borrowing its pixel data.
+    pub fn of(image: &'a Image<P>) -> ImgRef<'a, P>
+        where P: 'static
+    {
+        let width = image.width();
+        ImgRef {
+            data: image,
+            width: width,
+            height: image.height(),
+            row_stride: width as usize * P::channel_count() as usize
+        }
+    }
+
+    /// The width of this view, in pixels.
+    pub fn width(&self) -> u32 { self.width }
+
+    /// The height of this view, in pixels.
+    pub fn height(&self) -> u32 { self.height }
+
+    /// The pixel at `(x, y)`, relative to this view's origin.
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        *P::from_slice(self.subpixels(x, y))
+    }
+
+    /// All pixels in this view, paired with their `(x, y)` coordinate
+    /// relative to this view's origin.
+    pub fn pixels(&self) -> ImgRefPixels<'a, P> {
+        ImgRefPixels {
+            data: self.data,
+            width: self.width,
+            height: self.height,
+            row_stride: self.row_stride,
+            x: 0,
+            y: 0
+        }
+    }
+
+    /// A view over a `width x height` rectangle starting at `(x, y)`,
+    /// relative to this view's origin. Re-slices this view's data rather
+    /// than copying any of it.
+    pub fn sub_view(&self, x: u32, y: u32, width: u32, height: u32) -> ImgRef<'a, P> {
+        assert_in_bounds(x, y, width, height, self.width, self.height);
+        let offset = y as usize * self.row_stride + x as usize * P::channel_count() as usize;
+        // An empty rectangle at the far edge can start past the end of the data.
+        let offset = cmp::min(offset, self.data.len());
+        ImgRef {
+            data: &self.data[offset..],
+            width: width,
+            height: height,
+            row_stride: self.row_stride
+        }
+    }
+
+    fn subpixels(&self, x: u32, y: u32) -> &[P::Subpixel] {
+        let channels = P::channel_count() as usize;
+        let offset = y as usize * self.row_stride + x as usize * channels;
+        &self.data[offset..offset + channels]
+    }
+}
+
+impl<'a, P> ImgRefMut<'a, P>
+    where P: Pixel + 'a,
+          P::Subpixel: 'a
+{
+    /// A mutable view over the whole of `image`, borrowing its pixel data.
+    pub fn of(image: &'a mut Image<P>) -> ImgRefMut<'a, P>
+        where P: 'static
+    {
+        let width = image.width();
+        let height = image.height();
+        ImgRefMut {
+            data: image,
+            width: width,
+            height: height,
+            row_stride: width as usize * P::channel_count() as usize
+        }
+    }
+
+    /// The width of this view, in pixels.
+    pub fn width(&self) -> u32 { self.width }
+
+    /// The height of this view, in pixels.
+    pub fn height(&self) -> u32 { self.height }
+
+    /// The pixel at `(x, y)`, relative to this view's origin.
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        let channels = P::channel_count() as usize;
+        let offset = y as usize * self.row_stride + x as usize * channels;
+        *P::from_slice(&self.data[offset..offset + channels])
+    }
+
+    /// Overwrites the pixel at `(x, y)`, relative to this view's origin.
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        let channels = P::channel_count() as usize;
+        let offset = y as usize * self.row_stride + x as usize * channels;
+        self.data[offset..offset + channels].copy_from_slice(pixel.channels());
+    }
+
+    /// An immutable view over a `width x height` rectangle starting at
+    /// `(x, y)`, relative to this view's origin. Re-slices this view's data
+    /// rather than copying any of it.
+    pub fn sub_view(&self, x: u32, y: u32, width: u32, height: u32) -> ImgRef<'_, P> {
+        assert_in_bounds(x, y, width, height, self.width, self.height);
+        let offset = y as usize * self.row_stride + x as usize * P::channel_count() as usize;
+        let offset = cmp::min(offset, self.data.len());
+        ImgRef {
+            data: &self.data[offset..],
+            width: width,
+            height: height,
+            row_stride: self.row_stride
+        }
+    }
+
+    /// A mutable view over a `width x height` rectangle starting at
+    /// `(x, y)`, relative to this view's origin. Re-slices this view's data
+    /// rather than copying any of it.
+    pub fn sub_view_mut(&mut self, x: u32, y: u32, width: u32, height: u32) -> ImgRefMut<'_, P> {
+        assert_in_bounds(x, y, width, height, self.width, self.height);
+        let offset = y as usize * self.row_stride + x as usize * P::channel_count() as usize;
+        let offset = cmp::min(offset, self.data.len());
+        ImgRefMut {
+            data: &mut self.data[offset..],
+            width: width,
+            height: height,
+            row_stride: self.row_stride
+        }
+    }
+}
+
+/// Iterator over the pixels of an [`ImgRef`](struct.ImgRef.html), created by
+/// [`ImgRef::pixels`](struct.ImgRef.html#method.pixels).
+pub struct ImgRefPixels<'a, P>
+    where P: Pixel + 'a,
+          P::Subpixel: 'a
+{
+    data: &'a [P::Subpixel],
+    width: u32,
+    height: u32,
+    row_stride: usize,
+    x: u32,
+    y: u32
+}
+
+impl<'a, P> Iterator for ImgRefPixels<'a, P>
+    where P: Pixel + 'a,
+          P::Subpixel: 'a
+{
+    type Item = (u32, u32, P);
+
+    fn next(&mut self) -> Option<(u32, u32, P)> {
+        if self.y >= self.height || self.width == 0 {
+            return None;
+        }
+        let (x, y) = (self.x, self.y);
+        let channels = P::channel_count() as usize;
+        let offset = y as usize * self.row_stride + x as usize * channels;
+        let pixel = *P::from_slice(&self.data[offset..offset + channels]);
+
+        self.x += 1;
+        if self.x >= self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+
+        Some((x, y, pixel))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ImgRef, ImgRefMut};
+    use image::{GenericImage, ImageBuffer, Luma};
+    use definitions::Image;
+
+    fn test_image() -> Image<Luma<u8>> {
+        let mut image = ImageBuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                image.put_pixel(x, y, Luma([(y * 3 + x) as u8]));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_img_ref_get_pixel_matches_source_image() {
+        let image = test_image();
+        let view = ImgRef::of(&image);
+        for (x, y, pixel) in GenericImage::pixels(&image) {
+            assert_eq!(view.get_pixel(x, y), pixel);
+        }
+    }
+
+    #[test]
+    fn test_img_ref_pixels_yields_coordinates_in_row_major_order() {
+        let image = test_image();
+        let view = ImgRef::of(&image);
+        let collected: Vec<_> = view.pixels().collect();
+        assert_eq!(
+            collected,
+            vec![
+                (0, 0, Luma([0u8])), (1, 0, Luma([1u8])), (2, 0, Luma([2u8])),
+                (0, 1, Luma([3u8])), (1, 1, Luma([4u8])), (2, 1, Luma([5u8]))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_img_ref_sub_view_is_relative_to_its_origin() {
+        let image = test_image();
+        let view = ImgRef::of(&image).sub_view(1, 1, 2, 1);
+        assert_eq!(view.width(), 2);
+        assert_eq!(view.height(), 1);
+        assert_eq!(view.get_pixel(0, 0), Luma([4u8]));
+        assert_eq!(view.get_pixel(1, 0), Luma([5u8]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_img_ref_sub_view_panics_when_rectangle_extends_beyond_width() {
+        let image = test_image();
+        ImgRef::of(&image).sub_view(1, 0, 3, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_img_ref_mut_sub_view_panics_when_rectangle_extends_beyond_height() {
+        let mut image = test_image();
+        let view = ImgRefMut::of(&mut image);
+        view.sub_view(0, 1, 3, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_img_ref_mut_sub_view_mut_panics_when_rectangle_extends_beyond_height() {
+        let mut image = test_image();
+        let mut view = ImgRefMut::of(&mut image);
+        view.sub_view_mut(0, 1, 3, 2);
+    }
+
+    #[test]
+    fn test_img_ref_mut_put_pixel_writes_through_to_source_image() {
+        let mut image = test_image();
+        {
+            let mut view = ImgRefMut::of(&mut image);
+            view.put_pixel(1, 0, Luma([99u8]));
+        }
+        assert_eq!(image.get_pixel(1, 0), &Luma([99u8]));
+    }
+
+    #[test]
+    fn test_img_ref_mut_sub_view_mut_writes_through_to_source_image() {
+        let mut image = test_image();
+        {
+            let mut view = ImgRefMut::of(&mut image);
+            let mut sub = view.sub_view_mut(1, 1, 2, 1);
+            sub.put_pixel(0, 0, Luma([42u8]));
+        }
+        assert_eq!(image.get_pixel(1, 1), &Luma([42u8]));
+    }
+}